@@ -15,11 +15,51 @@
  * limitations under the License.
  */
 
-use std::{any, collections::HashMap};
+use std::{any, collections::HashMap, env, fs, time::Duration};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 
 use super::protocol::ProtocolConfig;
 use super::service::ServiceConfig;
 
+/// Path to the config file is read from this env var, falling back to
+/// [`DEFAULT_CONFIG_PATH`] when unset.
+const CONFIG_PATH_ENV: &str = "DUBBO_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "./dubbo.yaml";
+/// Env var overrides for service/protocol fields are namespaced under this
+/// prefix, e.g. `DUBBO_PROTOCOLS_TRIPLE_PORT=8889`.
+const ENV_PREFIX: &str = "DUBBO_";
+
+/// Shape of the `service`/`protocols` maps as they appear in the YAML/TOML
+/// config file, before being turned into the real [`ServiceConfig`] /
+/// [`ProtocolConfig`] structs via their builders.
+#[derive(Debug, Default, Deserialize)]
+struct FileConfig {
+    #[serde(default)]
+    service: HashMap<String, FileServiceConfig>,
+    #[serde(default)]
+    protocols: HashMap<String, FileProtocolConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FileServiceConfig {
+    group: Option<String>,
+    serializer: Option<String>,
+    version: Option<String>,
+    protocol_names: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FileProtocolConfig {
+    ip: Option<String>,
+    port: Option<String>,
+    max_encoding_message_size: Option<usize>,
+    max_decoding_message_size: Option<usize>,
+    compression_min_message_size: Option<usize>,
+    compression_incompressible_content_types: Option<Vec<String>>,
+}
+
 /// used to storage all structed config, from some source: cmd, file..;
 /// Impl Config trait, business init by read Config trait
 #[allow(dead_code)]
@@ -29,6 +69,9 @@ pub struct RootConfig {
     pub service: HashMap<String, ServiceConfig>,
     pub protocols: HashMap<String, ProtocolConfig>,
     pub data: HashMap<String, Box<dyn any::Any>>,
+    /// The raw parsed config file, kept around so `get::<T>` can pull out
+    /// arbitrary sub-trees that don't have a dedicated accessor.
+    tree: Option<serde_yaml::Value>,
 }
 
 pub fn get_global_config() -> RootConfig {
@@ -44,46 +87,145 @@ impl RootConfig {
             service: HashMap::new(),
             protocols: HashMap::new(),
             data: HashMap::new(),
+            tree: None,
         }
     }
 
+    /// Layered config load: a YAML/TOML file (path from `DUBBO_CONFIG`,
+    /// defaulting to `./dubbo.yaml`) is applied first, then environment
+    /// variable overrides on top of it. Any programmatic builder calls made
+    /// by the caller after `load()` returns win over both, since they
+    /// mutate `service`/`protocols` directly.
     pub fn load(&mut self) {
-        let service_config = ServiceConfig::default()
-            .group("test".to_string())
-            .serializer("json".to_string())
-            .version("1.0.0".to_string())
-            .protocol_names("triple".to_string())
-            .name("echo".to_string());
-
-        let triple_config = ProtocolConfig::default()
-            .name("triple".to_string())
-            .ip("0.0.0.0".to_string())
-            .port("8888".to_string());
-
-        let service_config = service_config.add_protocol_configs(triple_config);
-        self.service.insert("echo".to_string(), service_config);
-        self.service.insert(
-            "helloworld.Greeter".to_string(),
-            ServiceConfig::default()
-                .group("test".to_string())
-                .serializer("json".to_string())
-                .version("1.0.0".to_string())
-                .name("helloworld.Greeter".to_string())
-                .protocol_names("triple".to_string()),
-        );
-        self.protocols.insert(
-            "triple".to_string(),
-            ProtocolConfig::default()
-                .name("triple".to_string())
-                .ip("0.0.0.0".to_string())
-                .port("8889".to_string()),
-        );
-        // 通过环境变量读取某个文件。加在到内存中
-        self.data.insert(
-            "dubbo.provider.url".to_string(),
-            Box::new("dubbo://127.0.0.1:8888/?serviceName=hellworld".to_string()),
-        );
-        // self.data.insert("dubbo.consume.", v)
+        if let Some(file_config) = self.read_config_file() {
+            self.apply_file_config(file_config);
+        }
+        self.apply_env_overrides();
+    }
+
+    fn config_file_path() -> String {
+        env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    fn read_config_file(&mut self) -> Option<FileConfig> {
+        let path = Self::config_file_path();
+        let contents = fs::read_to_string(&path).ok()?;
+
+        if path.ends_with(".toml") {
+            let value: toml::Value = toml::from_str(&contents).ok()?;
+            self.tree = serde_yaml::to_value(&value).ok();
+            value.try_into().ok()
+        } else {
+            let value: serde_yaml::Value = serde_yaml::from_str(&contents).ok()?;
+            self.tree = Some(value.clone());
+            serde_yaml::from_value(value).ok()
+        }
+    }
+
+    fn apply_file_config(&mut self, file_config: FileConfig) {
+        for (name, service) in file_config.service {
+            let mut service_config = ServiceConfig::default().name(name.clone());
+            if let Some(group) = service.group {
+                service_config = service_config.group(group);
+            }
+            if let Some(serializer) = service.serializer {
+                service_config = service_config.serializer(serializer);
+            }
+            if let Some(version) = service.version {
+                service_config = service_config.version(version);
+            }
+            if let Some(protocol_names) = service.protocol_names {
+                service_config = service_config.protocol_names(protocol_names);
+            }
+            self.service.insert(name, service_config);
+        }
+
+        for (name, protocol) in file_config.protocols {
+            let mut protocol_config = ProtocolConfig::default().name(name.clone());
+            if let Some(ip) = protocol.ip {
+                protocol_config = protocol_config.ip(ip);
+            }
+            if let Some(port) = protocol.port {
+                protocol_config = protocol_config.port(port);
+            }
+            if let Some(max) = protocol.max_encoding_message_size {
+                protocol_config = protocol_config.max_encoding_message_size(max);
+            }
+            if let Some(max) = protocol.max_decoding_message_size {
+                protocol_config = protocol_config.max_decoding_message_size(max);
+            }
+            if let Some(min) = protocol.compression_min_message_size {
+                protocol_config = protocol_config.compression_min_message_size(min);
+            }
+            if let Some(types) = protocol.compression_incompressible_content_types {
+                protocol_config = protocol_config.compression_incompressible_content_types(types);
+            }
+            self.protocols.insert(name, protocol_config);
+        }
+    }
+
+    /// Applies `DUBBO_PROTOCOLS_<NAME>_<FIELD>` / `DUBBO_SERVICE_<NAME>_<FIELD>`
+    /// overrides, e.g. `DUBBO_PROTOCOLS_TRIPLE_PORT=8889`; an unrecognised
+    /// `<FIELD>` under those two prefixes is ignored. Any other key under
+    /// the `DUBBO_` prefix is stashed into `data` verbatim.
+    fn apply_env_overrides(&mut self) {
+        for (key, value) in env::vars() {
+            let Some(rest) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+
+            match rest.splitn(3, '_').collect::<Vec<_>>().as_slice() {
+                ["PROTOCOLS", name, field] => {
+                    let name = name.to_lowercase();
+                    let protocol_config = self
+                        .protocols
+                        .remove(&name)
+                        .unwrap_or_else(|| ProtocolConfig::default().name(name.clone()));
+                    let protocol_config = match field.to_lowercase().as_str() {
+                        "ip" => protocol_config.ip(value),
+                        "port" => protocol_config.port(value),
+                        "name" => protocol_config.name(value),
+                        "max_encoding_message_size" => match value.parse() {
+                            Ok(max) => protocol_config.max_encoding_message_size(max),
+                            Err(_) => protocol_config,
+                        },
+                        "max_decoding_message_size" => match value.parse() {
+                            Ok(max) => protocol_config.max_decoding_message_size(max),
+                            Err(_) => protocol_config,
+                        },
+                        "compression_min_message_size" => match value.parse() {
+                            Ok(min) => protocol_config.compression_min_message_size(min),
+                            Err(_) => protocol_config,
+                        },
+                        "compression_incompressible_content_types" => protocol_config
+                            .compression_incompressible_content_types(
+                                value.split(',').map(|s| s.trim().to_string()).collect(),
+                            ),
+                        _ => protocol_config,
+                    };
+                    self.protocols.insert(name, protocol_config);
+                }
+                ["SERVICE", name, field] => {
+                    let name = name.to_lowercase();
+                    let service_config = self
+                        .service
+                        .remove(&name)
+                        .unwrap_or_else(|| ServiceConfig::default().name(name.clone()));
+                    let service_config = match field.to_lowercase().as_str() {
+                        "group" => service_config.group(value),
+                        "serializer" => service_config.serializer(value),
+                        "version" => service_config.version(value),
+                        "protocol_names" => service_config.protocol_names(value),
+                        "name" => service_config.name(value),
+                        _ => service_config,
+                    };
+                    self.service.insert(name, service_config);
+                }
+                _ => {
+                    self.data.insert(key.clone(), Box::new(value));
+                }
+            }
+        }
     }
 }
 
@@ -113,6 +255,38 @@ impl Config for RootConfig {
             }
         }
     }
+
+    fn int(&self, key: String) -> Option<i64> {
+        match self.data.get(&key)?.downcast_ref::<i64>() {
+            Some(v) => Some(*v),
+            None => self.string(key).parse().ok(),
+        }
+    }
+
+    fn duration(&self, key: String) -> Option<Duration> {
+        if let Some(v) = self.data.get(&key).and_then(|v| v.downcast_ref::<Duration>()) {
+            return Some(*v);
+        }
+        humantime::parse_duration(&self.string(key)).ok()
+    }
+
+    fn list(&self, key: String) -> Vec<String> {
+        match self.data.get(&key) {
+            Some(val) => match val.downcast_ref::<Vec<String>>() {
+                Some(v) => v.clone(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, key: String) -> Option<T> {
+        let tree = self.tree.as_ref()?;
+        let value = key
+            .split('.')
+            .try_fold(tree, |value, part| value.get(part))?;
+        serde_yaml::from_value(value.clone()).ok()
+    }
 }
 
 pub trait BusinessConfig {
@@ -123,4 +297,8 @@ pub trait BusinessConfig {
 pub trait Config {
     fn bool(&self, key: String) -> bool;
     fn string(&self, key: String) -> String;
+    fn int(&self, key: String) -> Option<i64>;
+    fn duration(&self, key: String) -> Option<Duration>;
+    fn list(&self, key: String) -> Vec<String>;
+    fn get<T: DeserializeOwned>(&self, key: String) -> Option<T>;
 }
@@ -0,0 +1,70 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/// Per-protocol listener configuration, one entry per `protocols.<name>` in
+/// the config file (see [`crate::config::RootConfig`]).
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolConfig {
+    pub name: String,
+    pub ip: String,
+    pub port: String,
+    /// Caps on an encoded/decoded message frame; `None` leaves the codec's
+    /// own default in place. See `triple::server::compression::MessageSizeLimits`.
+    pub max_encoding_message_size: Option<usize>,
+    pub max_decoding_message_size: Option<usize>,
+    /// Compression gate; `None` leaves the codec's own default in place. See
+    /// `triple::server::compression::CompressionConfig`.
+    pub compression_min_message_size: Option<usize>,
+    pub compression_incompressible_content_types: Option<Vec<String>>,
+}
+
+impl ProtocolConfig {
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn ip(mut self, ip: String) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn port(mut self, port: String) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn max_encoding_message_size(mut self, max: usize) -> Self {
+        self.max_encoding_message_size = Some(max);
+        self
+    }
+
+    pub fn max_decoding_message_size(mut self, max: usize) -> Self {
+        self.max_decoding_message_size = Some(max);
+        self
+    }
+
+    pub fn compression_min_message_size(mut self, min: usize) -> Self {
+        self.compression_min_message_size = Some(min);
+        self
+    }
+
+    pub fn compression_incompressible_content_types(mut self, types: Vec<String>) -> Self {
+        self.compression_incompressible_content_types = Some(types);
+        self
+    }
+}
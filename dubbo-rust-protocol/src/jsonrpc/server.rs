@@ -16,6 +16,8 @@
  */
 
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{net::SocketAddr, pin::Pin, task::Poll};
 
 use futures::ready;
@@ -39,12 +41,143 @@ where
     Box::pin(fut)
 }
 
+/// Which HTTP version(s) [`JsonRpcServer`] will serve a connection with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpProtocol {
+    /// Only ever speak HTTP/1.x, as the server did before h2 support existed.
+    Http1Only,
+    /// Only ever speak HTTP/2, assuming prior-knowledge h2c.
+    Http2Only,
+    /// Peek the connection's first bytes and serve h2 to clients that send
+    /// the HTTP/2 client preface, h1 otherwise.
+    Auto,
+}
+
+impl Default for HttpProtocol {
+    fn default() -> Self {
+        HttpProtocol::Auto
+    }
+}
+
+/// The first 14 bytes of the fixed connection preface an HTTP/2 client sends
+/// before any frames (the whole preface is `PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`),
+/// used to distinguish h2 prior-knowledge connections from HTTP/1.x ones.
+const H2_CLIENT_PREFACE: &[u8] = b"PRI * HTTP/2.0";
+
+/// How long to wait for all of [`H2_CLIENT_PREFACE`] to arrive before giving up and
+/// falling back to HTTP/1.1, so a client that never sends enough bytes
+/// can't park the detection task forever.
+const PROTOCOL_DETECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+async fn detect_protocol(stream: &AddrStream) -> std::io::Result<HttpProtocol> {
+    match tokio::time::timeout(PROTOCOL_DETECT_TIMEOUT, peek_preface(stream)).await {
+        Ok(result) => result,
+        Err(_elapsed) => Ok(HttpProtocol::Http1Only),
+    }
+}
+
+/// Peeks the connection without consuming bytes, retrying until
+/// `H2_CLIENT_PREFACE.len()` bytes are available (they may arrive split across TCP segments) or
+/// the peeked prefix already diverges from the preface.
+async fn peek_preface(stream: &AddrStream) -> std::io::Result<HttpProtocol> {
+    let mut buf = [0u8; H2_CLIENT_PREFACE.len()];
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == buf.len() {
+            return Ok(if buf == *H2_CLIENT_PREFACE {
+                HttpProtocol::Http2Only
+            } else {
+                HttpProtocol::Http1Only
+            });
+        }
+        if n > 0 && buf[..n] != H2_CLIENT_PREFACE[..n] {
+            return Ok(HttpProtocol::Http1Only);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    }
+}
+
+/// Shared state behind a [`Shutdown`] future: once `count` drops to one
+/// (only the `Shutdown` future's own handle left), every connection that
+/// held a clone has finished draining.
+///
+/// `count` is tracked explicitly instead of reading `Arc::strong_count`,
+/// because two `DrainToken`s dropped concurrently on different tasks could
+/// otherwise both read the same pre-decrement count, both see it isn't the
+/// "last one" value, and both skip waking — `fetch_sub` makes the "am I the
+/// last one" decision and the decrement itself a single atomic operation, so
+/// that race can't happen.
+struct DrainState {
+    waker: Mutex<Option<std::task::Waker>>,
+    count: std::sync::atomic::AtomicUsize,
+}
+
+struct DrainToken(Arc<DrainState>);
+
+impl DrainToken {
+    fn new() -> Self {
+        Self(Arc::new(DrainState {
+            waker: Mutex::new(None),
+            count: std::sync::atomic::AtomicUsize::new(1),
+        }))
+    }
+}
+
+impl Clone for DrainToken {
+    fn clone(&self) -> Self {
+        self.0.count.fetch_add(1, Ordering::AcqRel);
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for DrainToken {
+    fn drop(&mut self) {
+        // The previous value was 2 exactly when this decrement is the one
+        // taking it down to 1 (the watcher's own handle) — check the value
+        // `fetch_sub` itself returns instead of a separate read afterwards.
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 2 {
+            if let Some(waker) = self.0.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Future returned by [`JsonRpcServer::shutdown`]. Completes once every
+/// connection that was in flight when shutdown was requested has finished.
+pub struct Shutdown {
+    drain: DrainToken,
+}
+
+impl Future for Shutdown {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        // Register before checking so a connection finishing concurrently
+        // can't drop the count to 1 in the gap between the two.
+        *self.drain.0.waker.lock().unwrap() = Some(cx.waker().clone());
+        if self.drain.0.count.load(Ordering::Acquire) == 1 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
 pin_project! {
    pub struct JsonRpcServer<S> {
         #[pin]
         incoming: AddrIncoming,
         rt_handle: tokio::runtime::Handle,
-        service: S
+        service: S,
+        shutting_down: Arc<AtomicBool>,
+        drain: DrainToken,
+        protocol: HttpProtocol,
+        // The waker from the accept loop's most recent poll. The loop parks
+        // inside `poll_accept`, which only wakes on socket readiness, so
+        // `shutdown()` needs this to force a re-poll and make the loop
+        // notice `shutting_down` even if no new connection ever arrives.
+        accept_waker: Arc<Mutex<Option<std::task::Waker>>>,
     }
 }
 
@@ -58,9 +191,20 @@ impl<S> JsonRpcServer<S> {
             incoming: incoming,
             rt_handle: handle,
             service,
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            drain: DrainToken::new(),
+            protocol: HttpProtocol::default(),
+            accept_waker: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Forces which HTTP version(s) this server accepts, instead of the
+    /// default of auto-detecting h1 vs h2 per connection.
+    pub fn protocol(mut self, protocol: HttpProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
     fn poll_next(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
@@ -68,6 +212,22 @@ impl<S> JsonRpcServer<S> {
         let me = self.project();
         me.incoming.poll_accept(cx)
     }
+
+    /// Stops accepting new connections and returns a future that completes
+    /// once every connection already in flight has finished (each is flipped
+    /// from `poll_without_shutdown` to a graceful HTTP shutdown).
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        // Force the accept loop to be re-polled even if the listener never
+        // sees another connection attempt, so it notices `shutting_down`
+        // and drops its own `drain` handle promptly instead of hanging.
+        if let Some(waker) = self.accept_waker.lock().unwrap().take() {
+            waker.wake();
+        }
+        Shutdown {
+            drain: self.drain.clone(),
+        }
+    }
 }
 
 type SrvFut<R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'static>>;
@@ -77,7 +237,10 @@ pin_project! {
     where S: tower::Service<HttpRequest<Body>,Response = HttpResponse<Body>,Error = StdError, Future = SrvFut<HttpResponse<Body>,StdError>>
     {
         #[pin]
-        connection: Connection<IO,S>
+        connection: Connection<IO,S>,
+        shutting_down: Arc<AtomicBool>,
+        graceful_started: bool,
+        _drain: DrainToken,
     }
 }
 
@@ -94,7 +257,15 @@ where
     type Output = Result<(), hyper::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        self.project().connection.poll_without_shutdown(cx)
+        let this = self.project();
+        if this.shutting_down.load(Ordering::SeqCst) {
+            if !*this.graceful_started {
+                this.connection.as_mut().graceful_shutdown();
+                *this.graceful_started = true;
+            }
+            return this.connection.poll(cx);
+        }
+        this.connection.poll_without_shutdown(cx)
     }
 }
 
@@ -115,19 +286,55 @@ where
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
+        // Refresh the waker `shutdown()` uses to force a re-poll on every
+        // call, since the one passed in can change between polls.
+        *self.accept_waker.lock().unwrap() = Some(cx.waker().clone());
+
         loop {
+            if self.shutting_down.load(Ordering::SeqCst) {
+                // Release this server's handle on the shared drain state (by
+                // swapping in an unrelated one), so the `Shutdown` future only
+                // waits on the connections still in flight.
+                *self.as_mut().project().drain = DrainToken::new();
+                return Poll::Ready(Ok(()));
+            }
+
             let ret = ready!(self.as_mut().poll_next(cx));
             match ret {
                 Some(Ok(stream)) => {
                     trace!("Get conn {}", stream.remote_addr());
 
-                    let connection = Http::new()
-                        .http1_only(true)
-                        .http1_keep_alive(true)
-                        .serve_connection(stream, self.service.clone());
+                    let service = self.service.clone();
+                    let shutting_down = self.shutting_down.clone();
+                    let drain = self.drain.clone();
+                    let protocol = self.protocol;
+                    self.rt_handle.spawn(async move {
+                        let protocol = match protocol {
+                            HttpProtocol::Auto => detect_protocol(&stream)
+                                .await
+                                .unwrap_or(HttpProtocol::Http1Only),
+                            forced => forced,
+                        };
 
-                    let one_conn = OneConnection { connection };
-                    self.rt_handle.spawn(one_conn);
+                        let mut builder = Http::new();
+                        match protocol {
+                            HttpProtocol::Http1Only | HttpProtocol::Auto => {
+                                builder.http1_only(true).http1_keep_alive(true);
+                            }
+                            HttpProtocol::Http2Only => {
+                                builder.http2_only(true);
+                            }
+                        };
+
+                        let connection = builder.serve_connection(stream, service);
+                        let one_conn = OneConnection {
+                            connection,
+                            shutting_down,
+                            graceful_started: false,
+                            _drain: drain,
+                        };
+                        one_conn.await
+                    });
                 }
                 Some(Err(e)) => return Poll::Ready(Err(e.into())),
                 None => return Poll::Ready(Err("option none".into())),
@@ -188,6 +395,16 @@ where
                 }
                 let data = data?;
 
+                // The JSON-RPC 2.0 spec allows the body to be an array of
+                // request objects (a batch) instead of a single object;
+                // sniff that shape before deciding how to dispatch.
+                if let Ok(serde_json::Value::Array(items)) =
+                    serde_json::from_slice::<serde_json::Value>(&data)
+                {
+                    let body = call_batch(items, inner_service).await;
+                    return Ok(HttpResponse::builder().body(body.into()).unwrap());
+                }
+
                 let request = JsonRpcRequest::from_slice(data.to_vec());
 
                 if let Err(ref e) = request {
@@ -211,4 +428,95 @@ where
             Ok(HttpResponse::builder().body(Body::empty()).unwrap())
         })
     }
+}
+
+/// Builds the standard JSON-RPC 2.0 error object body for a malformed
+/// request, e.g. `-32600` (Invalid Request) or `-32700` (Parse error).
+fn jsonrpc_error_body(id: Option<&serde_json::Value>, code: i32, message: &str) -> String {
+    let id = id.cloned().unwrap_or(serde_json::Value::Null);
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": id,
+    })
+    .to_string()
+}
+
+/// Dispatches a JSON-RPC batch: each element is run through `inner_service`
+/// concurrently, notifications (no `id` member) are dropped from the
+/// response, and the results are re-assembled into a single JSON array
+/// in request order.
+async fn call_batch<S>(items: Vec<serde_json::Value>, inner_service: S) -> String
+where
+    S: tower::Service<
+            JsonRpcRequest,
+            Response = JsonRpcResponse,
+            Error = StdError,
+            Future = SrvFut<JsonRpcResponse, StdError>,
+        > + Clone
+        + Send
+        + 'static,
+{
+    if items.is_empty() {
+        return jsonrpc_error_body(None, -32600, "Invalid Request");
+    }
+
+    let calls = items.into_iter().map(|item| {
+        let mut service = inner_service.clone();
+        async move {
+            let id = item.get("id").cloned();
+            // `Value::get("id")` also returns `None` for anything that isn't
+            // a JSON object (numbers, strings, arrays, ...), so an `is_object`
+            // check is needed too — otherwise a malformed non-object batch
+            // item (e.g. the spec's own `[1,2,3]` example) would be
+            // classified as a notification and silently dropped instead of
+            // producing an Invalid Request error.
+            let is_notification = item.is_object() && id.is_none();
+
+            // `item` is already a valid `serde_json::Value` (it came out of
+            // parsing the batch array), so `to_vec` can't fail here; the
+            // only way this can fail is `from_slice` rejecting its shape
+            // (missing `method`/`jsonrpc`), which is an Invalid Request,
+            // not a parse error.
+            let request = serde_json::to_vec(&item)
+                .ok()
+                .and_then(|bytes| JsonRpcRequest::from_slice(bytes).ok());
+
+            let request = match request {
+                Some(request) => request,
+                None if is_notification => return None,
+                None => return Some(jsonrpc_error_body(id.as_ref(), -32600, "Invalid Request")),
+            };
+
+            if is_notification {
+                let _ = service.call(request).await;
+                return None;
+            }
+
+            let response: Result<String, StdError> = async {
+                let res = service.call(request).await?;
+                Ok(res.to_string()?)
+            }
+            .await;
+
+            Some(response.unwrap_or_else(|_| {
+                jsonrpc_error_body(id.as_ref(), -32603, "Internal error")
+            }))
+        }
+    });
+
+    let responses: Vec<String> = futures::future::join_all(calls)
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if responses.is_empty() {
+        // Per the JSON-RPC 2.0 spec, a batch made up entirely of
+        // notifications must not return an empty array, it must produce no
+        // response body at all.
+        return String::new();
+    }
+
+    format!("[{}]", responses.join(","))
 }
\ No newline at end of file
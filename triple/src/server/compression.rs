@@ -0,0 +1,316 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use std::io::{self, Read, Write};
+
+use bytes::BytesMut;
+use flate2::read::DeflateDecoder;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{read::GzDecoder, Compression};
+
+/// The `grpc-encoding` / `grpc-accept-encoding` header name used to negotiate
+/// which compression codec the triple protocol should use for a call.
+pub const ENCODING_HEADER: &str = "grpc-encoding";
+pub const ACCEPT_ENCODING_HEADER: &str = "grpc-accept-encoding";
+
+/// Compression codecs supported by the triple protocol.
+///
+/// `Identity` means "no compression" and is always implicitly supported by
+/// both sides, so it never needs to be advertised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Zstd,
+    Brotli,
+}
+
+impl CompressionEncoding {
+    /// The value used on the wire for `grpc-encoding` / `grpc-accept-encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionEncoding::Identity => "identity",
+            CompressionEncoding::Gzip => "gzip",
+            CompressionEncoding::Deflate => "deflate",
+            CompressionEncoding::Zstd => "zstd",
+            CompressionEncoding::Brotli => "br",
+        }
+    }
+
+    /// Parses a single `grpc-encoding`/`grpc-accept-encoding` token, ignoring
+    /// anything this server doesn't know how to handle.
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "identity" => Some(CompressionEncoding::Identity),
+            "gzip" => Some(CompressionEncoding::Gzip),
+            "deflate" => Some(CompressionEncoding::Deflate),
+            "zstd" => Some(CompressionEncoding::Zstd),
+            "br" => Some(CompressionEncoding::Brotli),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a comma-separated `grpc-accept-encoding` (or `grpc-encoding`) header
+/// value into the list of encodings this process understands, preserving the
+/// order the peer sent them in (its preference order).
+pub fn parse_encoding_list(header_value: &str) -> Vec<CompressionEncoding> {
+    header_value
+        .split(',')
+        .filter_map(CompressionEncoding::from_str)
+        .collect()
+}
+
+/// Picks the decoder to use for an incoming request's `grpc-encoding` header.
+pub fn decoder_for_request(grpc_encoding: Option<&str>) -> Option<CompressionEncoding> {
+    match grpc_encoding.and_then(CompressionEncoding::from_str) {
+        Some(CompressionEncoding::Identity) | None => None,
+        Some(other) => Some(other),
+    }
+}
+
+/// Picks which codec to compress the response with, given the client's
+/// `grpc-accept-encoding` header and the encodings this server is configured
+/// to use. Falls back to identity (i.e. `None`, meaning "don't compress") if
+/// the two sides share nothing in common.
+pub fn negotiate_response_encoding(
+    grpc_accept_encoding: Option<&str>,
+    server_supported: &[CompressionEncoding],
+) -> Option<CompressionEncoding> {
+    let accepted = match grpc_accept_encoding {
+        Some(header) => parse_encoding_list(header),
+        None => return None,
+    };
+
+    server_supported
+        .iter()
+        .find(|supported| {
+            **supported != CompressionEncoding::Identity && accepted.contains(supported)
+        })
+        .copied()
+}
+
+/// Controls when `compress` actually bothers compressing a frame.
+///
+/// Compressing is pure overhead for tiny frames (the codec's own framing
+/// outweighs anything it could save) and for payloads that are already
+/// compressed or otherwise high-entropy, so both cases are worth skipping
+/// before spending CPU on them.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Frames smaller than this (in encoded bytes) are sent uncompressed.
+    pub min_message_size: usize,
+    /// Content-type prefixes that are known to not benefit from
+    /// compression (images, archives, already-compressed blobs, ...).
+    pub incompressible_content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_message_size: 32,
+            incompressible_content_types: vec![
+                "image/".to_string(),
+                "video/".to_string(),
+                "audio/".to_string(),
+                "application/zip".to_string(),
+                "application/gzip".to_string(),
+                "application/x-gzip".to_string(),
+                "application/x-7z-compressed".to_string(),
+                "application/x-rar-compressed".to_string(),
+            ],
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Builds a config from a protocol config's `compression_min_message_size`
+    /// / `compression_incompressible_content_types` overrides (e.g.
+    /// `config::protocol::ProtocolConfig`), falling back to this type's
+    /// `Default` for whichever field wasn't set.
+    pub fn from_config(
+        min_message_size: Option<usize>,
+        incompressible_content_types: Option<Vec<String>>,
+    ) -> Self {
+        let default = Self::default();
+        Self {
+            min_message_size: min_message_size.unwrap_or(default.min_message_size),
+            incompressible_content_types: incompressible_content_types
+                .unwrap_or(default.incompressible_content_types),
+        }
+    }
+
+    /// Whether a frame of `len` bytes with the given content-type hint is
+    /// worth running through `compress` at all.
+    pub fn should_compress(&self, len: usize, content_type: Option<&str>) -> bool {
+        if len < self.min_message_size {
+            return false;
+        }
+
+        if let Some(content_type) = content_type {
+            if self
+                .incompressible_content_types
+                .iter()
+                .any(|prefix| content_type.starts_with(prefix.as_str()))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Caps on how large an encoded message frame is allowed to be, guarding
+/// against unbounded memory use from a malicious or buggy peer (e.g. a
+/// decompression bomb on the decode side).
+///
+/// `None` means unlimited; both limits default to a sane 4 MiB otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageSizeLimits {
+    pub max_encoding_message_size: Option<usize>,
+    pub max_decoding_message_size: Option<usize>,
+}
+
+/// 4 MiB, the default cap on an encoded/decoded message frame.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+impl Default for MessageSizeLimits {
+    fn default() -> Self {
+        Self {
+            max_encoding_message_size: Some(DEFAULT_MAX_MESSAGE_SIZE),
+            max_decoding_message_size: Some(DEFAULT_MAX_MESSAGE_SIZE),
+        }
+    }
+}
+
+impl MessageSizeLimits {
+    /// Builds limits from a protocol config's `max_encoding_message_size` /
+    /// `max_decoding_message_size` overrides (e.g.
+    /// `config::protocol::ProtocolConfig`), falling back to
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`] for whichever side wasn't set.
+    pub fn from_config(
+        max_encoding_message_size: Option<usize>,
+        max_decoding_message_size: Option<usize>,
+    ) -> Self {
+        Self {
+            max_encoding_message_size: max_encoding_message_size.or(Some(DEFAULT_MAX_MESSAGE_SIZE)),
+            max_decoding_message_size: max_decoding_message_size.or(Some(DEFAULT_MAX_MESSAGE_SIZE)),
+        }
+    }
+}
+
+/// Compresses `len` bytes from `src` into `dst` using `encoding`.
+///
+/// `Identity` is a no-op copy; callers should generally avoid calling this at
+/// all for identity and just encode straight into the destination buffer
+/// instead.
+pub fn compress(
+    encoding: CompressionEncoding,
+    src: &mut BytesMut,
+    dst: &mut BytesMut,
+    len: usize,
+) -> Result<(), io::Error> {
+    match encoding {
+        CompressionEncoding::Identity => {
+            dst.extend_from_slice(&src[..len]);
+            Ok(())
+        }
+        CompressionEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(dst.writer(), Compression::default());
+            encoder.write_all(&src[..len])?;
+            encoder.finish()?;
+            Ok(())
+        }
+        CompressionEncoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(dst.writer(), Compression::default());
+            encoder.write_all(&src[..len])?;
+            encoder.finish()?;
+            Ok(())
+        }
+        CompressionEncoding::Zstd => {
+            let compressed = zstd::stream::encode_all(&src[..len], 0)?;
+            dst.extend_from_slice(&compressed);
+            Ok(())
+        }
+        CompressionEncoding::Brotli => {
+            let mut out = dst.writer();
+            let mut input = &src[..len];
+            brotli::BrotliCompress(&mut input, &mut out, &brotli::enc::BrotliEncoderParams::default())?;
+            Ok(())
+        }
+    }
+}
+
+/// Decompresses `src` (the full body of a compressed frame) into `dst`.
+///
+/// `max_decompressed_size`, when set, bounds how many bytes the decompressor
+/// is allowed to produce: a small compressed frame that would expand past
+/// the cap (a decompression bomb) is rejected instead of being fully
+/// inflated into memory.
+pub fn decompress(
+    encoding: CompressionEncoding,
+    src: &[u8],
+    dst: &mut BytesMut,
+    max_decompressed_size: Option<usize>,
+) -> Result<(), io::Error> {
+    match encoding {
+        CompressionEncoding::Identity => read_capped(src, dst, max_decompressed_size),
+        CompressionEncoding::Gzip => read_capped(GzDecoder::new(src), dst, max_decompressed_size),
+        CompressionEncoding::Deflate => {
+            read_capped(DeflateDecoder::new(src), dst, max_decompressed_size)
+        }
+        CompressionEncoding::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(src)?;
+            read_capped(decoder, dst, max_decompressed_size)
+        }
+        CompressionEncoding::Brotli => {
+            read_capped(brotli::Decompressor::new(src, 4096), dst, max_decompressed_size)
+        }
+    }
+}
+
+/// Reads all of `r` into `dst`, erroring out instead of continuing to read
+/// once more than `max` bytes have come out the other end.
+fn read_capped<R: Read>(
+    mut r: R,
+    dst: &mut BytesMut,
+    max: Option<usize>,
+) -> Result<(), io::Error> {
+    let Some(max) = max else {
+        let mut out = Vec::new();
+        r.read_to_end(&mut out)?;
+        dst.extend_from_slice(&out);
+        return Ok(());
+    };
+
+    // Read one byte past the cap so an over-size message is detected
+    // instead of silently truncated.
+    let mut limited = r.take(max as u64 + 1);
+    let mut out = Vec::new();
+    limited.read_to_end(&mut out)?;
+
+    if out.len() as u64 > max as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "decompressed message exceeds max_decoding_message_size",
+        ));
+    }
+
+    dst.extend_from_slice(&out);
+    Ok(())
+}
@@ -0,0 +1,142 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use bytes::{Buf, Bytes, BytesMut};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use http::HeaderMap;
+use tonic::Status;
+
+use super::compression::{decoder_for_request, decompress, CompressionEncoding, MessageSizeLimits, ENCODING_HEADER};
+use crate::codec::{DecodeBuf, Decoder};
+
+/// Decodes a request body, picking the decompressor from the request's own
+/// `grpc-encoding` header (falling back to no compression if it's absent or
+/// unrecognized) instead of requiring the caller to resolve it up front.
+pub fn decode_request<D, B>(
+    decoder: D,
+    body: B,
+    request_headers: &HeaderMap,
+    size_limits: MessageSizeLimits,
+) -> impl Stream<Item = Result<D::Item, Status>>
+where
+    D: Decoder<Error = Status>,
+    B: Stream<Item = Result<Bytes, Status>>,
+{
+    let grpc_encoding = request_headers
+        .get(ENCODING_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let compression_encoding = decoder_for_request(grpc_encoding);
+    decode(decoder, body, compression_encoding, size_limits)
+}
+
+/// Decodes a stream of raw, length-prefixed triple/gRPC message frames
+/// (`[compressed-flag u8][length u32][payload]`) into decoded items,
+/// decompressing each frame with `compression_encoding` when its
+/// compressed-flag byte is set.
+///
+/// `max_decoding_message_size` is enforced twice: against the frame's
+/// declared length as soon as its header has arrived, before waiting for
+/// the rest of its payload, and again against
+/// the number of bytes a decompressor actually produces, so a small
+/// compressed frame can't be used to allocate far more memory than the
+/// limit allows (a decompression bomb).
+pub fn decode<D, B>(
+    mut decoder: D,
+    body: B,
+    compression_encoding: Option<CompressionEncoding>,
+    size_limits: MessageSizeLimits,
+) -> impl Stream<Item = Result<D::Item, Status>>
+where
+    D: Decoder<Error = Status>,
+    B: Stream<Item = Result<Bytes, Status>>,
+{
+    async_stream::stream! {
+        futures_util::pin_mut!(body);
+
+        let mut frame_buf = BytesMut::new();
+        let mut decompressed_buf = BytesMut::new();
+
+        loop {
+            match body.next().await {
+                Some(Ok(chunk)) => frame_buf.extend_from_slice(&chunk),
+                Some(Err(err)) => {
+                    yield Err(err);
+                    return;
+                }
+                None => break,
+            }
+
+            while frame_buf.len() >= super::consts::HEADER_SIZE {
+                let compressed = frame_buf[0] != 0;
+                let len = u32::from_be_bytes(frame_buf[1..super::consts::HEADER_SIZE].try_into().unwrap()) as usize;
+
+                if let Some(max) = size_limits.max_decoding_message_size {
+                    if len > max {
+                        yield Err(Status::out_of_range(format!(
+                            "received message of {len} bytes exceeding max_decoding_message_size of {max} bytes"
+                        )));
+                        return;
+                    }
+                }
+
+                if frame_buf.len() < super::consts::HEADER_SIZE + len {
+                    // Haven't buffered the whole frame yet.
+                    break;
+                }
+
+                frame_buf.advance(super::consts::HEADER_SIZE);
+                let payload = frame_buf.split_to(len);
+
+                decompressed_buf.clear();
+                if compressed {
+                    match compression_encoding {
+                        Some(encoding) => {
+                            if decompress(
+                                encoding,
+                                &payload,
+                                &mut decompressed_buf,
+                                size_limits.max_decoding_message_size,
+                            )
+                            .is_err()
+                            {
+                                yield Err(Status::out_of_range("decompressed message too large"));
+                                return;
+                            }
+                        }
+                        None => {
+                            yield Err(Status::internal(
+                                "received a compressed frame but no compression codec is configured",
+                            ));
+                            return;
+                        }
+                    }
+                } else {
+                    decompressed_buf.extend_from_slice(&payload);
+                }
+
+                match decoder.decode(&mut DecodeBuf::new(&mut decompressed_buf)) {
+                    Ok(Some(item)) => yield Ok(item),
+                    Ok(None) => {}
+                    Err(status) => {
+                        yield Err(status);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -19,18 +19,58 @@ use std::{pin::Pin, task::Poll};
 use bytes::{BufMut, Bytes, BytesMut};
 use futures_core::{Stream, TryStream};
 use futures_util::{ready, StreamExt, TryStreamExt};
+use http::HeaderMap;
 use http_body::Body;
 use pin_project::pin_project;
 use tonic::Status;
 
-use super::compression::{compress, CompressionEncoding};
+use super::compression::{
+    compress, negotiate_response_encoding, CompressionConfig, CompressionEncoding,
+    MessageSizeLimits, ACCEPT_ENCODING_HEADER,
+};
 use crate::codec::{EncodeBuf, Encoder};
 
+/// Encodes a server response, picking the compression codec from the
+/// intersection of `server_supported` and the request's own
+/// `grpc-accept-encoding` header (falling back to identity if the two sides
+/// share nothing in common) instead of requiring the caller to resolve it
+/// up front.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_server_response<E, B>(
+    encoder: E,
+    body: B,
+    request_headers: &HeaderMap,
+    server_supported: &[CompressionEncoding],
+    compression_config: CompressionConfig,
+    content_type: Option<&'static str>,
+    size_limits: MessageSizeLimits,
+) -> EncodeBody<impl Stream<Item = Result<Bytes, Status>>>
+where
+    E: Encoder<Error = Status>,
+    B: Stream<Item = Result<E::Item, Status>>,
+{
+    let grpc_accept_encoding = request_headers
+        .get(ACCEPT_ENCODING_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let compression_encoding = negotiate_response_encoding(grpc_accept_encoding, server_supported);
+    encode_server(
+        encoder,
+        body,
+        compression_encoding,
+        compression_config,
+        content_type,
+        size_limits,
+    )
+}
+
 #[allow(unused_must_use)]
 pub fn encode<E, B>(
     mut encoder: E,
     resp_body: B,
     compression_encoding: Option<CompressionEncoding>,
+    compression_config: CompressionConfig,
+    content_type: Option<&'static str>,
+    size_limits: MessageSizeLimits,
 ) -> impl TryStream<Ok = Bytes, Error = Status>
 where
     E: Encoder<Error = Status>,
@@ -41,9 +81,10 @@ where
         futures_util::pin_mut!(resp_body);
 
         let (enable_compress, mut uncompression_buf) = match compression_encoding {
-            Some(CompressionEncoding::Gzip) => (true, BytesMut::with_capacity(super::consts::BUFFER_SIZE)),
-            None => (false, BytesMut::new())
+            Some(CompressionEncoding::Identity) | None => (false, BytesMut::new()),
+            Some(_) => (true, BytesMut::with_capacity(super::consts::BUFFER_SIZE)),
         };
+        let mut compressed_scratch = BytesMut::new();
 
         loop {
             match resp_body.next().await {
@@ -54,22 +95,48 @@ where
                         buf.advance_mut(super::consts::HEADER_SIZE);
                     }
 
-                    if enable_compress {
-                        uncompression_buf.clear();
-
-                        encoder.encode(item, &mut EncodeBuf::new(&mut uncompression_buf)).map_err(|_e| tonic::Status::internal("encode error"));
+                    uncompression_buf.clear();
+                    let encode_buf = if enable_compress { &mut uncompression_buf } else { &mut buf };
+                    encoder.encode(item, &mut EncodeBuf::new(encode_buf)).map_err(|_e| tonic::Status::internal("encode error"));
 
+                    let frame_is_compressed = if enable_compress {
                         let len = uncompression_buf.len();
-                        compress(compression_encoding.unwrap(), &mut uncompression_buf, &mut buf, len).map_err(|_| tonic::Status::internal("compress error"));
-                    } else {
-                        encoder.encode(item, &mut EncodeBuf::new(&mut buf)).map_err(|_e| tonic::Status::internal("encode error"));
-                    }
+                        if compression_config.should_compress(len, content_type) {
+                            compressed_scratch.clear();
+                            compress(compression_encoding.unwrap(), &mut uncompression_buf, &mut compressed_scratch, len)
+                                .map_err(|_| tonic::Status::internal("compress error"));
 
+                            if compressed_scratch.len() < len {
+                                buf.extend_from_slice(&compressed_scratch);
+                                true
+                            } else {
+                                // Compression didn't actually shrink the frame, send it as-is.
+                                buf.extend_from_slice(&uncompression_buf);
+                                false
+                            }
+                        } else {
+                            buf.extend_from_slice(&uncompression_buf);
+                            false
+                        }
+                    } else {
+                        false
+                    };
 
                     let len = buf.len() - super::consts::HEADER_SIZE;
+
+                    if let Some(max) = size_limits.max_encoding_message_size {
+                        if len > max {
+                            buf.split_to(len + super::consts::HEADER_SIZE);
+                            yield Err(Status::out_of_range(format!(
+                                "encoded message of {len} bytes exceeds max_encoding_message_size of {max} bytes"
+                            )));
+                            continue;
+                        }
+                    }
+
                     {
                         let mut buf = &mut buf[..super::consts::HEADER_SIZE];
-                        buf.put_u8(enable_compress as u8);
+                        buf.put_u8(frame_is_compressed as u8);
                         buf.put_u32(len as u32);
                     }
 
@@ -86,12 +153,23 @@ pub fn encode_server<E, B>(
     encoder: E,
     body: B,
     compression_encoding: Option<CompressionEncoding>,
+    compression_config: CompressionConfig,
+    content_type: Option<&'static str>,
+    size_limits: MessageSizeLimits,
 ) -> EncodeBody<impl Stream<Item = Result<Bytes, Status>>>
 where
     E: Encoder<Error = Status>,
     B: Stream<Item = Result<E::Item, Status>>,
 {
-    let s = encode(encoder, body, compression_encoding).into_stream();
+    let s = encode(
+        encoder,
+        body,
+        compression_encoding,
+        compression_config,
+        content_type,
+        size_limits,
+    )
+    .into_stream();
     EncodeBody::new_server(s)
 }
 
@@ -99,12 +177,23 @@ pub fn encode_client<E, B>(
     encoder: E,
     body: B,
     compression_encoding: Option<CompressionEncoding>,
+    compression_config: CompressionConfig,
+    content_type: Option<&'static str>,
+    size_limits: MessageSizeLimits,
 ) -> EncodeBody<impl Stream<Item = Result<Bytes, Status>>>
 where
     E: Encoder<Error = Status>,
     B: Stream<Item = E::Item>,
 {
-    let s = encode(encoder, body.map(Ok), compression_encoding).into_stream();
+    let s = encode(
+        encoder,
+        body.map(Ok),
+        compression_encoding,
+        compression_config,
+        content_type,
+        size_limits,
+    )
+    .into_stream();
     EncodeBody::new_client(s)
 }
 